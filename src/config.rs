@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::{env, path::Path, path::PathBuf};
+use swc_ecma_parser::{Syntax, TsConfig};
+
+/// Crawl configuration, loaded once at startup; `main` copies the fields each
+/// spawned `handle` task needs into that task.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub token: TokenSource,
+    #[serde(default)]
+    pub orgs: Filter,
+    #[serde(default)]
+    pub repos: Filter,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    #[serde(default)]
+    pub syntax: SyntaxConfig,
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+    /// Stack size given to each parser worker thread. Sweeping this (e.g.
+    /// 1 MiB, 2 MiB, 8 MiB) identifies the minimal input depth that
+    /// overflows the parser's recursion.
+    #[serde(default = "default_parser_stack_size_bytes")]
+    pub parser_stack_size_bytes: usize,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file: {}", path.display()))?;
+
+        let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+
+        let config = if is_toml {
+            toml::from_str(&raw)
+                .with_context(|| format!("failed to parse config file: {}", path.display()))?
+        } else {
+            serde_yaml::from_str(&raw)
+                .with_context(|| format!("failed to parse config file: {}", path.display()))?
+        };
+
+        Ok(config)
+    }
+
+    pub fn resolve_token(&self) -> Result<String> {
+        match &self.token {
+            TokenSource::Env { var } => env::var(var)
+                .with_context(|| format!("environment variable `{}` is not set", var)),
+            TokenSource::Literal { value } => Ok(value.clone()),
+        }
+    }
+}
+
+/// Where to read the GitHub token from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum TokenSource {
+    Env { var: String },
+    Literal { value: String },
+}
+
+/// A glob-based allow/deny filter over org or repo names. An empty `allow`
+/// list means "allow everything not explicitly denied".
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Filter {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, name: &str) -> bool {
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|pat| glob_matches(pat, name));
+        let denied = self.deny.iter().any(|pat| glob_matches(pat, name));
+
+        allowed && !denied
+    }
+}
+
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    Pattern::new(pattern)
+        .map(|pattern| pattern.matches(name))
+        .unwrap_or(false)
+}
+
+/// Which TypeScript dialect features to parse with, fed into
+/// `Syntax::Typescript`. Also sent across the parser worker-process
+/// boundary (see `check_module_in_subprocess` in `main.rs`).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SyntaxConfig {
+    #[serde(default)]
+    pub tsx: bool,
+    #[serde(default)]
+    pub decorators: bool,
+}
+
+impl SyntaxConfig {
+    pub fn to_syntax(self) -> Syntax {
+        Syntax::Typescript(TsConfig {
+            tsx: self.tsx,
+            decorators: self.decorators,
+            ..Default::default()
+        })
+    }
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from(".data")
+}
+
+fn default_max_concurrency() -> usize {
+    8
+}
+
+fn default_parser_stack_size_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_filter_allows_everything() {
+        let filter = Filter::default();
+        assert!(filter.matches("kdy1"));
+    }
+
+    #[test]
+    fn allow_list_restricts_to_matching_names() {
+        let filter = Filter {
+            allow: vec!["kdy1".into(), "swc-*".into()],
+            deny: vec![],
+        };
+        assert!(filter.matches("kdy1"));
+        assert!(filter.matches("swc-project"));
+        assert!(!filter.matches("other"));
+    }
+
+    #[test]
+    fn deny_list_wins_even_if_allowed() {
+        let filter = Filter {
+            allow: vec!["kdy1".into()],
+            deny: vec!["kdy1".into()],
+        };
+        assert!(!filter.matches("kdy1"));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::Repo;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The outcome of checking a single file, persisted to the `parse_results`
+/// table so a crawl can be queried for regressions afterwards instead of
+/// aborting on the first failure. Also serialized across the parser
+/// worker-process boundary (see `check_module_in_subprocess` in `main.rs`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Outcome {
+    Success,
+    ParseError {
+        message: String,
+        span_lo: u32,
+        span_hi: u32,
+    },
+    /// The parser worker process was killed (e.g. by `SIGSEGV`) rather than
+    /// exiting normally, which — given the bounded stack its parse thread
+    /// was spawned with — almost always means it overflowed its stack.
+    /// A panic of the worker's parse thread is recorded as this outcome too.
+    StackExhaustion,
+}
+
+/// Shared handle to the results database: a single connection behind a
+/// mutex, so it can be cloned into every spawned `handle` task.
+#[derive(Clone)]
+pub struct DbCtx {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl DbCtx {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open database: {}", path.display()))?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS repos (
+                id INTEGER PRIMARY KEY,
+                full_name TEXT NOT NULL UNIQUE,
+                clone_url TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS files (
+                id INTEGER PRIMARY KEY,
+                repo_id INTEGER NOT NULL REFERENCES repos(id),
+                path TEXT NOT NULL,
+                UNIQUE(repo_id, path)
+            );
+            CREATE TABLE IF NOT EXISTS parse_results (
+                id INTEGER PRIMARY KEY,
+                repo_id INTEGER NOT NULL REFERENCES repos(id),
+                file_id INTEGER REFERENCES files(id),
+                outcome TEXT NOT NULL,
+                message TEXT,
+                span_lo INTEGER,
+                span_hi INTEGER,
+                checked_at INTEGER NOT NULL
+            );",
+        )
+        .context("failed to initialize results database schema")?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Records (or updates) `repo`, returning its row id.
+    pub fn record_repo(&self, repo: &Repo) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO repos (full_name, clone_url) VALUES (?1, ?2)
+             ON CONFLICT(full_name) DO UPDATE SET clone_url = excluded.clone_url",
+            params![repo.full_name, repo.clone_url],
+        )
+        .with_context(|| format!("failed to record repo: {}", repo.full_name))?;
+
+        conn.query_row(
+            "SELECT id FROM repos WHERE full_name = ?1",
+            params![repo.full_name],
+            |row| row.get(0),
+        )
+        .with_context(|| format!("failed to look up repo id: {}", repo.full_name))
+    }
+
+    /// Records that `repo_id` could not be cloned at all.
+    pub fn record_clone_failure(&self, repo_id: i64, message: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO parse_results (repo_id, file_id, outcome, message, span_lo, span_hi, checked_at)
+             VALUES (?1, NULL, 'clone-failure', ?2, NULL, NULL, ?3)",
+            params![repo_id, message, now_unix()],
+        )
+        .context("failed to record clone failure")?;
+
+        Ok(())
+    }
+
+    /// Records the parse outcome of `path` within `repo_id`.
+    pub fn record_file_outcome(&self, repo_id: i64, path: &str, outcome: &Outcome) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO files (repo_id, path) VALUES (?1, ?2)
+             ON CONFLICT(repo_id, path) DO NOTHING",
+            params![repo_id, path],
+        )
+        .with_context(|| format!("failed to record file: {}", path))?;
+
+        let file_id: i64 = conn
+            .query_row(
+                "SELECT id FROM files WHERE repo_id = ?1 AND path = ?2",
+                params![repo_id, path],
+                |row| row.get(0),
+            )
+            .with_context(|| format!("failed to look up file id: {}", path))?;
+
+        let (kind, message, span_lo, span_hi) = match outcome {
+            Outcome::Success => ("success", None, None, None),
+            Outcome::ParseError {
+                message,
+                span_lo,
+                span_hi,
+            } => ("parse-error", Some(message.as_str()), Some(*span_lo), Some(*span_hi)),
+            Outcome::StackExhaustion => ("stack-exhaustion", None, None, None),
+        };
+
+        conn.execute(
+            "INSERT INTO parse_results (repo_id, file_id, outcome, message, span_lo, span_hi, checked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![repo_id, file_id, kind, message, span_lo, span_hi, now_unix()],
+        )
+        .with_context(|| format!("failed to record outcome for: {}", path))?;
+
+        Ok(())
+    }
+}
+
+fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs() as i64
+}
@@ -0,0 +1,211 @@
+use crate::{Contents, Org, Repo, Tree};
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use github_rs::client::{Executor, Github};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// The subset of the GitHub API the crawler needs, extracted so the crawl
+/// pipeline (pagination, filtering, `handle`, `handle_via_api`) can be
+/// exercised against canned data instead of live credentials.
+#[cfg_attr(test, mockall::automock)]
+pub trait Forge: Send + Sync {
+    fn organizations(&self, page: u32) -> Result<(Vec<Org>, HeaderMap)>;
+    fn repos_of_org(&self, org: &str, page: u32) -> Result<(Vec<Repo>, HeaderMap)>;
+    /// Lists the `.ts`/`.tsx` blobs of `full_name` (an `owner/repo` string)
+    /// via the GitHub trees API, without cloning the repository.
+    fn list_ts_files(&self, full_name: &str) -> Result<Vec<String>>;
+    /// Downloads the raw contents of `path` in `full_name`.
+    fn file_contents(&self, full_name: &str, path: &str) -> Result<String>;
+}
+
+/// The real `Forge`, backed by `github_rs`.
+pub struct GithubForge {
+    token: String,
+}
+
+impl GithubForge {
+    pub fn new(token: String) -> Self {
+        Self { token }
+    }
+}
+
+impl Forge for GithubForge {
+    fn organizations(&self, page: u32) -> Result<(Vec<Org>, HeaderMap)> {
+        let client = Github::new(self.token.clone()).unwrap();
+
+        // `github-rs`'s typed `Organizations` builder never grew a `.page()`
+        // query param, so pagination has to go through its raw-endpoint
+        // escape hatch instead.
+        let (headers, _, orgs) = client
+            .get()
+            .custom_endpoint(&format!("organizations?page={page}"))
+            .execute::<Vec<Org>>()
+            .map_err(|err| anyhow!("failed to fetch organizations: {:?}", err))?;
+
+        Ok((orgs.unwrap_or_default(), convert_headers(headers)))
+    }
+
+    fn repos_of_org(&self, org: &str, page: u32) -> Result<(Vec<Repo>, HeaderMap)> {
+        let client = Github::new(self.token.clone()).unwrap();
+
+        let (headers, _, repos) = client
+            .get()
+            .custom_endpoint(&format!("orgs/{org}/repos?page={page}"))
+            .execute::<Vec<Repo>>()
+            .map_err(|err| anyhow!("failed to fetch repos of {}: {:?}", org, err))?;
+
+        Ok((repos.unwrap_or_default(), convert_headers(headers)))
+    }
+
+    fn list_ts_files(&self, full_name: &str) -> Result<Vec<String>> {
+        let (owner, name) = full_name
+            .split_once('/')
+            .with_context(|| format!("invalid repository full name: {}", full_name))?;
+
+        let client = Github::new(self.token.clone()).unwrap();
+
+        // `github-rs` has no typed `Git`/`Trees` builder at all, so the
+        // recursive tree listing has to go through its raw-endpoint escape
+        // hatch instead.
+        let (_, _, tree) = client
+            .get()
+            .custom_endpoint(&format!("repos/{owner}/{name}/git/trees/HEAD?recursive=1"))
+            .execute::<Tree>()
+            .map_err(|err| anyhow!("failed to fetch tree of {}: {:?}", full_name, err))?;
+
+        let tree = tree.with_context(|| format!("no tree returned for {}", full_name))?;
+
+        Ok(tree
+            .tree
+            .into_iter()
+            .filter(|entry| {
+                entry.r#type == "blob"
+                    && (entry.path.ends_with(".ts") || entry.path.ends_with(".tsx"))
+            })
+            .map(|entry| entry.path)
+            .collect())
+    }
+
+    fn file_contents(&self, full_name: &str, path: &str) -> Result<String> {
+        let (owner, name) = full_name
+            .split_once('/')
+            .with_context(|| format!("invalid repository full name: {}", full_name))?;
+
+        let client = Github::new(self.token.clone()).unwrap();
+
+        let (_, _, contents) = client
+            .get()
+            .repos()
+            .owner(owner)
+            .repo(name)
+            .contents()
+            .path(path)
+            .execute::<Contents>()
+            .map_err(|err| anyhow!("failed to fetch contents of {}: {:?}", path, err))?;
+
+        let contents = contents.with_context(|| format!("no contents returned for {}", path))?;
+
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(contents.content.replace('\n', ""))
+            .with_context(|| format!("failed to decode base64 contents of {}", path))?;
+
+        String::from_utf8(decoded).with_context(|| format!("{} is not valid utf-8", path))
+    }
+}
+
+/// `github-rs` is pinned to the hyper-0.12 stack, so `execute()` hands back
+/// a `hyper::header::HeaderMap` rather than the `reqwest` one the rest of
+/// the crawler (pagination, rate-limit waiting) is written against. Rebuild
+/// an equivalent `reqwest` `HeaderMap` by round-tripping each header through
+/// its wire bytes instead of assuming the two crates' types interop.
+fn convert_headers(headers: hyper::header::HeaderMap) -> HeaderMap {
+    let mut converted = HeaderMap::new();
+
+    for (name, value) in headers.iter() {
+        let name = HeaderName::from_bytes(name.as_str().as_bytes());
+        let value = HeaderValue::from_bytes(value.as_bytes());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            converted.append(name, value);
+        }
+    }
+
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Fetcher;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn repos_of_org_follows_pagination_through_to_the_last_page() {
+        let mut mock = MockForge::new();
+
+        mock.expect_repos_of_org()
+            .withf(|org, page| org == "kdy1" && *page == 1)
+            .returning(|_, _| {
+                let mut headers = HeaderMap::new();
+                headers.insert("link", "<...>; rel=\"next\"".parse().unwrap());
+                Ok((
+                    vec![Repo {
+                        fork: false,
+                        archived: false,
+                        clone_url: "https://example.com/kdy1/a.git".into(),
+                        full_name: "kdy1/a".into(),
+                    }],
+                    headers,
+                ))
+            });
+
+        mock.expect_repos_of_org()
+            .withf(|org, page| org == "kdy1" && *page == 2)
+            .returning(|_, _| {
+                Ok((
+                    vec![Repo {
+                        fork: false,
+                        archived: false,
+                        clone_url: "https://example.com/kdy1/b.git".into(),
+                        full_name: "kdy1/b".into(),
+                    }],
+                    HeaderMap::new(),
+                ))
+            });
+
+        let fetcher = Fetcher::for_forge(Arc::new(mock));
+
+        let repos = fetcher.repos_of_org("kdy1".into()).await.unwrap();
+
+        assert_eq!(
+            repos.into_iter().map(|repo| repo.full_name).collect::<Vec<_>>(),
+            vec!["kdy1/a", "kdy1/b"],
+        );
+    }
+
+    #[tokio::test]
+    async fn api_mode_fetcher_methods_run_entirely_against_a_mock_forge() {
+        let mut mock = MockForge::new();
+
+        mock.expect_list_ts_files()
+            .withf(|full_name| full_name == "kdy1/a")
+            .returning(|_| Ok(vec!["src/index.ts".into(), "README.md".into()]));
+
+        mock.expect_file_contents()
+            .withf(|full_name, path| full_name == "kdy1/a" && path == "src/index.ts")
+            .returning(|_, _| Ok("export const x: number = 1;".into()));
+
+        let fetcher = Fetcher::for_forge(Arc::new(mock));
+        let repo = Repo {
+            fork: false,
+            archived: false,
+            clone_url: "https://example.com/kdy1/a.git".into(),
+            full_name: "kdy1/a".into(),
+        };
+
+        let files = fetcher.list_ts_files_of_repo(&repo).await.unwrap();
+        assert_eq!(files, vec!["src/index.ts", "README.md"]);
+
+        let src = fetcher.get_file_contents(&repo, &files[0]).await.unwrap();
+        assert_eq!(src, "export const x: number = 1;");
+    }
+}
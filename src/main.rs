@@ -1,29 +1,61 @@
+mod config;
+mod db;
+mod forge;
+
+use crate::{
+    config::Config,
+    db::{DbCtx, Outcome},
+    forge::{Forge, GithubForge},
+};
 use anyhow::{bail, Context, Result};
 use futures_util::{future::BoxFuture, FutureExt};
-use github_rs::client::{Executor, Github};
-use serde::Deserialize;
-use std::{env, path::Path};
-use swc_common::{
-    errors::{ColorConfig, Handler},
-    input::StringInput,
-    sync::Lrc,
-    SourceMap,
+use gix::{progress::Discard, remote::fetch::Shallow};
+use gix_sec::identity::Account;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    io::{Read, Write},
+    num::NonZeroU32,
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use swc_ecma_parser::{lexer::Lexer, Parser, Syntax};
+use swc_common::{input::StringInput, sync::Lrc, FileName, SourceFile, SourceMap};
+use swc_ecma_parser::{lexer::Lexer, Parser};
 use tempfile::TempDir;
-use tokio::{fs::read_dir, process::Command, spawn, task::spawn_blocking};
+use tokio::{fs::read_dir, spawn, sync::Semaphore, task::spawn_blocking};
+
+/// Set in the environment of a re-exec'd copy of this binary to make it act
+/// as a parser worker (see [`check_module_in_subprocess`]) instead of
+/// running the crawl.
+const PARSER_WORKER_ENV_VAR: &str = "STACK_CHECKER_PARSER_WORKER";
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let fetcher = Fetcher {
-        token: env::var("GITHUB_TOKEN").expect("Environment variable `GITHUB_TOKEN` is required"),
-    };
+    if env::var_os(PARSER_WORKER_ENV_VAR).is_some() {
+        return run_parser_worker();
+    }
+
+    let config_path =
+        env::var("CONFIG_PATH").unwrap_or_else(|_| "stack-checker.toml".to_string());
+    let config = Config::load(Path::new(&config_path))
+        .with_context(|| format!("failed to load config: {}", config_path))?;
+
+    let fetcher = Fetcher::new(config.resolve_token()?);
+
+    let db = DbCtx::open(&config.data_dir.join("results.sqlite3"))?;
 
     let args = env::args();
     let repos = if args.len() != 1 {
         let mut repos = vec![];
         let mut tasks = vec![];
         for arg in args.into_iter().skip(1) {
+            if !config.orgs.matches(&arg) {
+                continue;
+            }
             tasks.push(fetcher.repos_of_org(arg));
         }
         for task in tasks {
@@ -31,13 +63,30 @@ async fn main() -> Result<()> {
         }
         repos.into_iter().flatten().collect()
     } else {
-        fetcher.list_repositories().await?
+        fetcher.list_repositories(&config.orgs).await?
     };
 
+    let repos = repos
+        .into_iter()
+        .filter(|repo| config.repos.matches(&repo.full_name));
+
+    let semaphore = Arc::new(Semaphore::new(config.max_concurrency));
     let mut tasks = vec![];
 
     for repo in repos {
-        let task = spawn(async move { handle(repo).await });
+        let fetcher = fetcher.clone();
+        let db = db.clone();
+        let syntax = config.syntax;
+        let stack_size = config.parser_stack_size_bytes;
+        let data_dir = config.data_dir.clone();
+        let semaphore = semaphore.clone();
+        let task = spawn(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .context("max_concurrency semaphore was closed")?;
+            handle(fetcher, db, syntax, stack_size, &data_dir, repo).await
+        });
         tasks.push(task);
     }
 
@@ -59,48 +108,92 @@ struct Repo {
     pub fork: bool,
     pub archived: bool,
     pub clone_url: String,
+    pub full_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Tree {
+    pub tree: Vec<TreeEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TreeEntry {
+    pub path: String,
+    pub r#type: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Contents {
+    pub content: String,
 }
 
 #[derive(Clone)]
 struct Fetcher {
     token: String,
+    forge: Arc<dyn Forge>,
 }
 
 impl Fetcher {
+    pub fn new(token: String) -> Self {
+        Self {
+            forge: Arc::new(GithubForge::new(token.clone())),
+            token,
+        }
+    }
+
+    /// Builds a `Fetcher` around an arbitrary `Forge`, so tests can exercise
+    /// pagination and filtering logic against a `MockForge` instead of live
+    /// credentials.
+    #[cfg(test)]
+    pub(crate) fn for_forge(forge: Arc<dyn Forge>) -> Self {
+        Self {
+            token: String::new(),
+            forge,
+        }
+    }
+
     pub async fn repos_of_org(&self, name: String) -> Result<Vec<Repo>> {
-        let token = self.token.clone();
+        let forge = self.forge.clone();
 
         spawn_blocking(move || {
             eprintln!("Organization: {}", name);
 
-            let client = Github::new(token).unwrap();
-
-            let (_, _, repos) = match client
-                .get()
-                .orgs()
-                .org(&name)
-                .repos()
-                .execute::<Vec<Repo>>()
-            {
-                Ok(v) => v,
-                Err(err) => bail!("failed to fetch repository of organizations: {:?}", err),
-            };
+            let mut repos = vec![];
+            let mut page = 1u32;
+
+            loop {
+                let (page_repos, headers) = forge.repos_of_org(&name, page)?;
+                repos.extend(page_repos);
+                wait_for_rate_limit(&headers);
 
-            Ok(repos.unwrap_or_default())
+                if !has_next_page(&headers) {
+                    break;
+                }
+                page += 1;
+            }
+
+            Ok(repos)
         })
         .await?
     }
 
-    pub async fn list_repositories(&self) -> Result<Vec<Repo>> {
-        let token = self.token.clone();
+    pub async fn list_repositories(&self, org_filter: &config::Filter) -> Result<Vec<Repo>> {
+        let forge = self.forge.clone();
 
         let orgs = spawn_blocking(move || -> Result<_> {
-            let client = Github::new(token).unwrap();
-            let orgs = client.get().organizations().execute::<Vec<Org>>();
-            let (_, _, orgs) = match orgs {
-                Ok(v) => v,
-                Err(err) => bail!("failed to fetch oranizations: {:?}", err),
-            };
+            let mut orgs = vec![];
+            let mut page = 1u32;
+
+            loop {
+                let (page_orgs, headers) = forge.organizations(page)?;
+                orgs.extend(page_orgs);
+                wait_for_rate_limit(&headers);
+
+                if !has_next_page(&headers) {
+                    break;
+                }
+                page += 1;
+            }
 
             Ok(orgs)
         })
@@ -108,49 +201,191 @@ impl Fetcher {
 
         let mut buf = vec![];
 
-        if let Some(orgs) = orgs {
-            for org in orgs {
-                let repos = self.repos_of_org(org.login).await?;
-                buf.extend(
-                    repos
-                        .into_iter()
-                        .filter(|repo| !repo.archived && !repo.fork),
-                );
+        for org in orgs {
+            if !org_filter.matches(&org.login) {
+                continue;
             }
+
+            let repos = self.repos_of_org(org.login).await?;
+            buf.extend(
+                repos
+                    .into_iter()
+                    .filter(|repo| !repo.archived && !repo.fork),
+            );
         }
 
         Ok(buf)
     }
+
+    /// Lists the `.ts`/`.tsx` blobs of `repo` via the GitHub trees API, without
+    /// cloning the repository.
+    pub async fn list_ts_files_of_repo(&self, repo: &Repo) -> Result<Vec<String>> {
+        let forge = self.forge.clone();
+        let full_name = repo.full_name.clone();
+
+        spawn_blocking(move || forge.list_ts_files(&full_name)).await?
+    }
+
+    /// Downloads the raw contents of `path` in `repo` via the GitHub contents
+    /// API (`.../contents/{path}`), at the repository's default branch.
+    pub async fn get_file_contents(&self, repo: &Repo, path: &str) -> Result<String> {
+        let forge = self.forge.clone();
+        let full_name = repo.full_name.clone();
+        let path = path.to_string();
+
+        spawn_blocking(move || forge.file_contents(&full_name, &path)).await?
+    }
+}
+
+/// Returns whether the `Link` response header advertises a `rel="next"` page.
+fn has_next_page(headers: &HeaderMap) -> bool {
+    headers
+        .get("link")
+        .and_then(|v| v.to_str().ok())
+        .map(|link| {
+            link.split(',')
+                .any(|part| part.contains("rel=\"next\""))
+        })
+        .unwrap_or(false)
+}
+
+/// Blocks the current thread until GitHub's rate limit resets, if the
+/// response indicates it has been exhausted.
+fn wait_for_rate_limit(headers: &HeaderMap) {
+    let remaining = headers
+        .get("x-ratelimit-remaining")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u32>().ok());
+
+    if remaining != Some(0) {
+        return;
+    }
+
+    let reset_at = headers
+        .get("x-ratelimit-reset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+
+    let Some(reset_at) = reset_at else {
+        return;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs();
+
+    let wait = Duration::from_secs(reset_at.saturating_sub(now) + 1);
+    eprintln!("Rate limit exhausted, sleeping for {:?}", wait);
+    std::thread::sleep(wait);
 }
 
-async fn handle(repo: Repo) -> Result<()> {
-    let dir = git_pull(&repo).await?;
-    check_all_files(dir.path()).await?;
+async fn handle(
+    fetcher: Fetcher,
+    db: DbCtx,
+    syntax: config::SyntaxConfig,
+    stack_size: usize,
+    data_dir: &Path,
+    repo: Repo,
+) -> Result<()> {
+    let repo_id = db.record_repo(&repo)?;
+
+    if env::var_os("API_ONLY_MODE").is_some() {
+        return handle_via_api(&fetcher, &db, syntax, stack_size, repo_id, repo).await;
+    }
+
+    let dir = match git_pull(&repo, &fetcher.token, data_dir).await {
+        Ok(dir) => dir,
+        Err(err) => {
+            db.record_clone_failure(repo_id, &format!("{:?}", err))?;
+            return Ok(());
+        }
+    };
+    check_all_files(db, syntax, stack_size, repo_id, dir.path()).await?;
     Ok(())
 }
 
-async fn git_pull(repo: &Repo) -> Result<TempDir> {
+/// Checks `repo` without cloning it, by pulling each `.ts`/`.tsx` file's
+/// contents over the GitHub API instead of going through `git_pull` and
+/// `check_all_files`.
+async fn handle_via_api(
+    fetcher: &Fetcher,
+    db: &DbCtx,
+    syntax: config::SyntaxConfig,
+    stack_size: usize,
+    repo_id: i64,
+    repo: Repo,
+) -> Result<()> {
+    let files = fetcher.list_ts_files_of_repo(&repo).await?;
+
+    for path in files {
+        let src = fetcher.get_file_contents(&repo, &path).await?;
+        let db = db.clone();
+        spawn_blocking(move || check_file_contents(&db, syntax, stack_size, repo_id, &path, src))
+            .await??;
+    }
+
+    Ok(())
+}
+
+async fn git_pull(repo: &Repo, token: &str, data_dir: &Path) -> Result<TempDir> {
     eprintln!("Pulling {}", repo.clone_url);
 
-    let cur_dir = env::current_dir().context("failed to get current directory")?;
-    let tmp_dir = TempDir::new_in(&cur_dir.join(".data"))?;
+    let tmp_dir = TempDir::new_in(data_dir)?;
+
+    let clone_url = repo.clone_url.clone();
+    let token = token.to_string();
+    let dir_path = tmp_dir.path().to_path_buf();
+
+    spawn_blocking(move || -> Result<()> {
+        let prepare = gix::prepare_clone(clone_url.as_str(), &dir_path)
+            .with_context(|| format!("failed to prepare clone of {}", clone_url))?
+            .with_shallow(Shallow::DepthAtRemote(
+                NonZeroU32::new(1).expect("1 is non-zero"),
+            ))
+            .configure_connection(move |connection| {
+                connection.transport_mut().set_identity(Account {
+                    username: "x-access-token".into(),
+                    password: token.clone(),
+                });
+                Ok(())
+            });
+
+        let (mut checkout, _) = prepare
+            .fetch_then_checkout(Discard, &AtomicBool::new(false))
+            .with_context(|| format!("failed to fetch {}", clone_url))?;
+
+        checkout
+            .main_worktree(Discard, &AtomicBool::new(false))
+            .with_context(|| format!("failed to checkout {}", clone_url))?;
 
-    Command::new("git")
-        .arg("pull")
-        .arg("--depth")
-        .arg("1")
-        .arg(&repo.clone_url)
-        .arg(tmp_dir.path())
-        .output()
-        .await
-        .with_context(|| format!("failed to clone {}", repo.clone_url))?;
+        Ok(())
+    })
+    .await??;
 
     Ok(tmp_dir)
 }
 
-fn check_all_files(dir: &Path) -> BoxFuture<Result<()>> {
+/// Whether `path` is a TypeScript source file worth checking, by extension
+/// (`.ts` or `.tsx`) rather than `Path::ends_with`, which matches path
+/// *components* and would never match a mere suffix like `.ts`.
+fn is_ts_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("ts") | Some("tsx")
+    )
+}
+
+fn check_all_files(
+    db: DbCtx,
+    syntax: config::SyntaxConfig,
+    stack_size: usize,
+    repo_id: i64,
+    dir: &Path,
+) -> BoxFuture<Result<()>> {
+    let dir = dir.to_path_buf();
     async move {
-        let mut entries = read_dir(dir)
+        let mut entries = read_dir(&dir)
             .await
             .with_context(|| format!("failed to read dir: {}", dir.display()))?;
 
@@ -164,10 +399,12 @@ fn check_all_files(dir: &Path) -> BoxFuture<Result<()>> {
             let path = entry.path();
             let ty = entry.file_type().await?;
             if ty.is_dir() {
-                check_all_files(&path).await?;
-            } else if ty.is_file() && path.ends_with(".ts") {
+                check_all_files(db.clone(), syntax, stack_size, repo_id, &path).await?;
+            } else if ty.is_file() && is_ts_file(&path) {
                 let path = path.clone();
-                spawn_blocking(move || check_file(&path)).await??;
+                let db = db.clone();
+                spawn_blocking(move || check_file(&db, syntax, stack_size, repo_id, &path))
+                    .await??;
             }
         }
 
@@ -176,21 +413,184 @@ fn check_all_files(dir: &Path) -> BoxFuture<Result<()>> {
     .boxed()
 }
 
-fn check_file(path: &Path) -> Result<()> {
-    let cm: Lrc<SourceMap> = Default::default();
-    let handler = Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(cm.clone()));
+fn check_file(
+    db: &DbCtx,
+    syntax: config::SyntaxConfig,
+    stack_size: usize,
+    repo_id: i64,
+    path: &Path,
+) -> Result<()> {
+    let display_name = path.display().to_string();
+
+    let outcome = check_module_in_subprocess(stack_size, WorkerSource::File(path.to_path_buf()), syntax)?;
+
+    db.record_file_outcome(repo_id, &display_name, &outcome)
+}
+
+/// Like [`check_file`], but for source that was fetched in-memory (e.g. via
+/// the GitHub contents API) rather than read off disk. `name` is used as the
+/// virtual file name for diagnostics.
+fn check_file_contents(
+    db: &DbCtx,
+    syntax: config::SyntaxConfig,
+    stack_size: usize,
+    repo_id: i64,
+    name: &str,
+    src: String,
+) -> Result<()> {
+    let outcome = check_module_in_subprocess(
+        stack_size,
+        WorkerSource::Inline {
+            name: name.to_string(),
+            src,
+        },
+        syntax,
+    )?;
+
+    db.record_file_outcome(repo_id, name, &outcome)
+}
+
+/// Where a parser worker should read its module source from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum WorkerSource {
+    File(PathBuf),
+    Inline { name: String, src: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkerRequest {
+    source: WorkerSource,
+    syntax: config::SyntaxConfig,
+    stack_size: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorkerResponse {
+    outcome: Outcome,
+}
 
-    // Real usage
-    // let fm = cm
-    //     .load_file(Path::new("test.js"))
-    //     .expect("failed to load test.js");
+/// Parses `source` in a freshly re-exec'd copy of this binary (a "parser
+/// worker process") instead of a plain thread.
+///
+/// A real stack overflow trips Rust's guard-page handler, which aborts the
+/// *entire process* it occurs in — not just the offending thread — so a
+/// `std::thread` with a bounded stack can't be used to detect one: the
+/// crawl itself would go down with it. Running the parse in a child process
+/// means only that child is lost, and its death by signal (rather than a
+/// clean exit) is exactly the signal that the parser overflowed its stack.
+fn check_module_in_subprocess(
+    stack_size: usize,
+    source: WorkerSource,
+    syntax: config::SyntaxConfig,
+) -> Result<Outcome> {
+    let exe = env::current_exe().context("failed to resolve path to the current executable")?;
+
+    let mut child = Command::new(exe)
+        .env(PARSER_WORKER_ENV_VAR, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("failed to spawn parser worker process")?;
+
+    let request = WorkerRequest {
+        source,
+        syntax,
+        stack_size,
+    };
+    let request_json =
+        serde_json::to_vec(&request).context("failed to serialize parser worker request")?;
+
+    child
+        .stdin
+        .take()
+        .expect("child stdin was piped")
+        .write_all(&request_json)
+        .context("failed to write request to parser worker")?;
+
+    let output = child
+        .wait_with_output()
+        .context("failed to wait for parser worker")?;
+
+    if let Some(signal) = output.status.signal() {
+        eprintln!(
+            "parser worker killed by signal {} (most likely a stack overflow)",
+            signal
+        );
+        return Ok(Outcome::StackExhaustion);
+    }
+
+    if !output.status.success() {
+        bail!(
+            "parser worker exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    serde_json::from_slice::<WorkerResponse>(&output.stdout)
+        .context("failed to parse parser worker response")
+        .map(|response| response.outcome)
+}
 
-    let fm = cm
-        .load_file(path)
-        .with_context(|| format!("failed to load file: {}", path.display()))?;
+/// Entry point when this binary is re-exec'd as a parser worker: reads a
+/// single [`WorkerRequest`] from stdin and writes its [`WorkerResponse`] to
+/// stdout.
+fn run_parser_worker() -> Result<()> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .context("failed to read parser worker request from stdin")?;
 
+    let request: WorkerRequest =
+        serde_json::from_str(&input).context("failed to parse parser worker request")?;
+
+    let outcome = run_worker_request(request)?;
+
+    std::io::stdout()
+        .write_all(&serde_json::to_vec(&WorkerResponse { outcome })?)
+        .context("failed to write parser worker response")?;
+
+    Ok(())
+}
+
+/// Runs the parse itself on a thread with the requested bounded stack, so
+/// that (absent an overflow, which takes the whole process with it) an
+/// ordinary panic is still caught and reported as [`Outcome::StackExhaustion`]
+/// rather than bringing down the worker.
+fn run_worker_request(request: WorkerRequest) -> Result<Outcome> {
+    let WorkerRequest {
+        source,
+        syntax,
+        stack_size,
+    } = request;
+
+    let worker = std::thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || -> Result<Outcome> {
+            let cm: Lrc<SourceMap> = Default::default();
+            let fm = match source {
+                WorkerSource::File(path) => cm
+                    .load_file(&path)
+                    .with_context(|| format!("failed to load file: {}", path.display()))?,
+                WorkerSource::Inline { name, src } => cm.new_source_file(FileName::Custom(name), src),
+            };
+
+            Ok(check_module(fm, syntax))
+        })
+        .context("failed to spawn parser worker thread")?;
+
+    match worker.join() {
+        Ok(result) => result,
+        Err(_) => Ok(Outcome::StackExhaustion),
+    }
+}
+
+/// Parses `fm` as a TypeScript module, returning the outcome instead of
+/// panicking so a single unparseable file doesn't abort the whole crawl.
+fn check_module(fm: Lrc<SourceFile>, syntax: config::SyntaxConfig) -> Outcome {
     let lexer = Lexer::new(
-        Syntax::Typescript(Default::default()),
+        syntax.to_syntax(),
         Default::default(),
         StringInput::from(&*fm),
         None,
@@ -198,10 +598,53 @@ fn check_file(path: &Path) -> Result<()> {
 
     let mut parser = Parser::new_from(lexer);
 
-    let _module = parser
-        .parse_typescript_module()
-        .map_err(|e| e.into_diagnostic(&handler).emit())
-        .expect("Failed to parse module.");
+    match parser.parse_typescript_module() {
+        Ok(_module) => Outcome::Success,
+        Err(err) => {
+            let span = err.span();
+            Outcome::ParseError {
+                message: format!("{:?}", err.into_kind()),
+                span_lo: span.lo.0,
+                span_hi: span.hi.0,
+            }
+        }
+    }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_next_page_true_for_a_link_header_with_rel_next() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "link",
+            "<https://api.github.com/x?page=2>; rel=\"next\"".parse().unwrap(),
+        );
+        assert!(has_next_page(&headers));
+    }
+
+    #[test]
+    fn has_next_page_false_without_a_link_header() {
+        assert!(!has_next_page(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn has_next_page_false_for_a_link_header_without_rel_next() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "link",
+            "<https://api.github.com/x?page=1>; rel=\"last\"".parse().unwrap(),
+        );
+        assert!(!has_next_page(&headers));
+    }
+
+    #[test]
+    fn is_ts_file_matches_ts_and_tsx_by_extension_only() {
+        assert!(is_ts_file(Path::new("src/index.ts")));
+        assert!(is_ts_file(Path::new("src/component.tsx")));
+        assert!(!is_ts_file(Path::new("src/index.js")));
+        assert!(!is_ts_file(Path::new("ts")));
+    }
 }